@@ -0,0 +1,113 @@
+use crate::entity::Scene;
+
+/// Downsamples `src` into whatever target the caller's render pass points at,
+/// using a 13-tap box filter (Jorge Jimenez, "Next Generation Post Processing
+/// in Call of Duty: Advanced Warfare").
+///
+/// When `karis_average` is set, each of the filter's five 2x2 tap groups (the
+/// inner quad and the four corner quads) is weighted by `1 / (1 + luma)` before
+/// being averaged,
+/// which suppresses the fireflies that a plain box filter would otherwise
+/// smear across the whole bloom chain. This is only needed on the first
+/// downsample, where the source still contains the unclamped bright pixels.
+pub struct DownsampleRenderPass {
+    pipeline: wgpu::RenderPipeline,
+    bind_group: wgpu::BindGroup,
+}
+
+impl DownsampleRenderPass {
+    pub fn new(
+        device: &wgpu::Device,
+        src: &wgpu::TextureView,
+        dst_format: wgpu::TextureFormat,
+        karis_average: bool,
+    ) -> Self {
+        let shader = device.create_shader_module(&wgpu::include_wgsl!("downsample.wgsl"));
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Downsample Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Downsample Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Downsample Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(src),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Downsample Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Downsample Render Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: if karis_average {
+                    "fs_main_karis_average"
+                } else {
+                    "fs_main"
+                },
+                targets: &[dst_format.into()],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self {
+            pipeline,
+            bind_group,
+        }
+    }
+
+    pub fn update(&self, _queue: &wgpu::Queue, _scene: &Scene) {}
+
+    pub fn draw<'a>(&'a self, rpass: &mut wgpu::RenderPass<'a>) {
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_bind_group(0, &self.bind_group, &[]);
+        rpass.draw(0..3, 0..1);
+    }
+}
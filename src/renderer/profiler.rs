@@ -0,0 +1,150 @@
+/// Per-pass GPU timings for a single frame, gathered via
+/// `wgpu::Features::TIMESTAMP_QUERY`. Construction returns `None` when the
+/// device doesn't support the feature, so callers can treat profiling as
+/// optional rather than threading a `Result` through `Renderer::render`.
+pub struct GpuProfiler {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    readback_buffer: wgpu::Buffer,
+    capacity: u32,
+    timestamp_period: f32,
+    labels: Vec<String>,
+    /// Whether the most recent `begin_pass` actually wrote a start
+    /// timestamp. `end_pass` checks this rather than assuming its matching
+    /// `begin_pass` succeeded, since one dropped for exceeding `MAX_PASSES`
+    /// would otherwise leave `end_pass` writing over a query index an
+    /// earlier pass already finalized.
+    pass_open: bool,
+}
+
+impl GpuProfiler {
+    /// Labeled passes a single frame can record before further `begin_pass`
+    /// calls are silently dropped.
+    const MAX_PASSES: u32 = 16;
+
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+
+        let capacity = Self::MAX_PASSES * 2;
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("Gpu Profiler Query Set"),
+            ty: wgpu::QueryType::Timestamp,
+            count: capacity,
+        });
+
+        let buffer_size = (capacity as wgpu::BufferAddress) * std::mem::size_of::<u64>() as wgpu::BufferAddress;
+        let resolve_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Gpu Profiler Resolve Buffer"),
+            size: buffer_size,
+            usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Gpu Profiler Readback Buffer"),
+            size: buffer_size,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        Some(Self {
+            query_set,
+            resolve_buffer,
+            readback_buffer,
+            capacity,
+            timestamp_period: queue.get_timestamp_period(),
+            labels: Vec::new(),
+            pass_open: false,
+        })
+    }
+
+    /// Writes the start timestamp for a labeled pass. Call immediately
+    /// before `encoder.begin_render_pass`.
+    pub fn begin_pass(&mut self, encoder: &mut wgpu::CommandEncoder, label: impl Into<String>) {
+        let start = self.labels.len() as u32 * 2;
+        if start + 1 >= self.capacity {
+            // More passes this frame than the profiler was sized for;
+            // silently drop the rest rather than growing mid-frame. Leave
+            // `pass_open` false so the matching `end_pass` no-ops too,
+            // instead of writing a stray timestamp over the end query of
+            // the last pass that was recorded.
+            self.pass_open = false;
+            return;
+        }
+        encoder.write_timestamp(&self.query_set, start);
+        self.labels.push(label.into());
+        self.pass_open = true;
+    }
+
+    /// Writes the end timestamp for the most recently started pass. Call
+    /// once that pass's `wgpu::RenderPass` has been dropped. No-ops if the
+    /// matching `begin_pass` was itself dropped.
+    pub fn end_pass(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        if !std::mem::replace(&mut self.pass_open, false) {
+            return;
+        }
+        let Some(last) = self.labels.len().checked_sub(1) else {
+            return;
+        };
+        let end = last as u32 * 2 + 1;
+        if end >= self.capacity {
+            return;
+        }
+        encoder.write_timestamp(&self.query_set, end);
+    }
+
+    /// Resolves this frame's queries and blocks until the GPU has finished,
+    /// returning each labeled pass's duration in milliseconds. Submits its
+    /// own command buffer, so call after the frame's main encoder has been
+    /// submitted.
+    pub fn resolve(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<(String, f64)> {
+        if self.labels.is_empty() {
+            return Vec::new();
+        }
+
+        let query_count = self.labels.len() as u32 * 2;
+        let bytes = (query_count as wgpu::BufferAddress) * std::mem::size_of::<u64>() as wgpu::BufferAddress;
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        encoder.resolve_query_set(&self.query_set, 0..query_count, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(&self.resolve_buffer, 0, &self.readback_buffer, 0, bytes);
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = self.readback_buffer.slice(..bytes);
+        let (tx, rx) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = tx.send(result);
+        });
+        device.poll(wgpu::Maintain::Wait);
+
+        let timings = match rx.recv() {
+            Ok(Ok(())) => {
+                let timings = {
+                    let mapped = slice.get_mapped_range();
+                    let timestamps: &[u64] = bytemuck::cast_slice(&mapped);
+                    self.labels
+                        .iter()
+                        .enumerate()
+                        .map(|(i, label)| {
+                            let ticks = timestamps[i * 2 + 1].saturating_sub(timestamps[i * 2]);
+                            let millis = ticks as f64 * self.timestamp_period as f64 / 1_000_000.0;
+                            (label.clone(), millis)
+                        })
+                        .collect()
+                };
+                // Only unmap on the path that actually mapped the buffer;
+                // calling it after a failed/dropped `map_async` trips
+                // wgpu's validation layer.
+                self.readback_buffer.unmap();
+                timings
+            }
+            // Mapping failed or the channel was dropped; report no timings
+            // for this frame rather than failing the caller.
+            _ => Vec::new(),
+        };
+        self.labels.clear();
+
+        timings
+    }
+}
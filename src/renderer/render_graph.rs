@@ -0,0 +1,313 @@
+use std::{collections::HashMap, rc::Rc};
+
+use crate::renderer::texture_pool::{PooledTexture, TextureKey, TexturePool};
+
+/// How a node's output slot gets its backing texture.
+pub enum Output {
+    /// Acquire a fresh texture from the pool, sized as a fraction of the
+    /// graph's base resolution (typically the surface size).
+    Allocated {
+        width_scale: f32,
+        height_scale: f32,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+    },
+    /// Reuse another slot's texture verbatim. Lets a node accumulate into a
+    /// slot an earlier node already produced, e.g. the bloom upsample chain
+    /// blending back down into mips the downsample chain wrote.
+    Alias(String),
+    /// Bound at execution time rather than allocated here, e.g. the
+    /// swapchain frame, which only exists once [`Renderer::render`] has
+    /// acquired it.
+    ///
+    /// [`Renderer::render`]: crate::renderer::Renderer::render
+    External,
+}
+
+/// One stage of the graph: a name, the slots it reads, and the slots it
+/// writes.
+pub struct NodeDesc {
+    pub name: String,
+    pub inputs: Vec<String>,
+    pub outputs: Vec<(String, Output)>,
+}
+
+impl NodeDesc {
+    pub fn new(name: impl Into<String>, inputs: Vec<String>, outputs: Vec<(String, Output)>) -> Self {
+        Self {
+            name: name.into(),
+            inputs,
+            outputs,
+        }
+    }
+}
+
+/// What a node actually does once its turn comes up in [`RenderGraph::order`]:
+/// record whatever render pass it owns into `encoder`, reading/writing slots
+/// through `graph` and `external`. Wired in via [`RenderGraph::set_record`]
+/// rather than carried on `NodeDesc` from the start, since a pass needs the
+/// texture views `RenderGraph::new` just allocated before it can even build
+/// its bind group — the record can't exist until after the graph does.
+pub type Record = Box<dyn Fn(&mut wgpu::CommandEncoder, &RenderGraph, &HashMap<&str, &wgpu::TextureView>, &str)>;
+
+/// A small render graph: nodes declare the named texture slots they read and
+/// write, and the graph topologically sorts them and allocates the
+/// intermediate textures. Each node's [`Record`] is wired in after the passes
+/// that back it exist (see [`RenderGraph::set_record`]); once every node has
+/// one, [`Renderer::render`] just replays `order()` instead of pattern-matching
+/// node names by hand.
+///
+/// [`Renderer::render`]: crate::renderer::Renderer::render
+pub struct RenderGraph {
+    slots: HashMap<String, Rc<PooledTexture>>,
+    /// Consumer names reachable from each producer name, kept around mostly
+    /// for the assertion in [`RenderGraph::new`] and for callers that want to
+    /// inspect the graph's shape.
+    edges: HashMap<String, Vec<String>>,
+    order: Vec<String>,
+    records: HashMap<String, Record>,
+}
+
+impl RenderGraph {
+    /// Builds the graph from `nodes`, topologically sorting them by their
+    /// declared input/output names and allocating each `Output::Allocated`
+    /// slot from `pool` at `base_width` x `base_height` scaled by the node's
+    /// `width_scale`/`height_scale`.
+    pub fn new(
+        device: &wgpu::Device,
+        pool: &TexturePool,
+        base_width: u32,
+        base_height: u32,
+        nodes: Vec<NodeDesc>,
+    ) -> Self {
+        let producer_of: HashMap<&str, &str> = nodes
+            .iter()
+            .flat_map(|node| node.outputs.iter().map(move |(slot, _)| (slot.as_str(), node.name.as_str())))
+            .collect();
+
+        let mut edges: HashMap<String, Vec<String>> = HashMap::new();
+        let mut in_degree: HashMap<&str, usize> = nodes.iter().map(|node| (node.name.as_str(), 0)).collect();
+        for node in &nodes {
+            for input in &node.inputs {
+                if let Some(&producer) = producer_of.get(input.as_str()) {
+                    edges.entry(producer.to_string()).or_default().push(node.name.clone());
+                    *in_degree.get_mut(node.name.as_str()).unwrap() += 1;
+                }
+            }
+        }
+
+        let mut ready: Vec<&str> = nodes
+            .iter()
+            .filter(|node| in_degree[node.name.as_str()] == 0)
+            .map(|node| node.name.as_str())
+            .collect();
+        let mut order = Vec::with_capacity(nodes.len());
+        while let Some(name) = ready.pop() {
+            order.push(name.to_string());
+            if let Some(consumers) = edges.get(name) {
+                for consumer in consumers {
+                    let degree = in_degree.get_mut(consumer.as_str()).unwrap();
+                    *degree -= 1;
+                    if *degree == 0 {
+                        ready.push(consumer.as_str());
+                    }
+                }
+            }
+        }
+        assert_eq!(order.len(), nodes.len(), "render graph has a cycle");
+
+        let mut slots: HashMap<String, Rc<PooledTexture>> = HashMap::new();
+        let mut live_keys: Vec<TextureKey> = Vec::new();
+        for name in &order {
+            let node = nodes.iter().find(|node| &node.name == name).unwrap();
+            for (slot_name, output) in &node.outputs {
+                let texture = match output {
+                    Output::Allocated {
+                        width_scale,
+                        height_scale,
+                        format,
+                        sample_count,
+                    } => {
+                        let width = ((base_width as f32) * width_scale).round().max(1.0) as u32;
+                        let height = ((base_height as f32) * height_scale).round().max(1.0) as u32;
+                        let usage = if *sample_count == 1 {
+                            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT
+                        } else {
+                            // Multisampled textures can't be bound as a
+                            // sampled texture; only their resolve target
+                            // needs `TEXTURE_BINDING`.
+                            wgpu::TextureUsages::RENDER_ATTACHMENT
+                        };
+                        live_keys.push(TextureKey {
+                            width,
+                            height,
+                            format: *format,
+                            usage,
+                            sample_count: *sample_count,
+                        });
+                        Rc::new(pool.acquire(device, slot_name, width, height, *format, usage, *sample_count))
+                    }
+                    Output::Alias(of) => slots
+                        .get(of.as_str())
+                        .unwrap_or_else(|| panic!("render graph slot `{slot_name}` aliases unknown slot `{of}`"))
+                        .clone(),
+                    Output::External => continue,
+                };
+                slots.insert(slot_name.clone(), texture);
+            }
+        }
+
+        // Anything the pool is still holding onto for a key none of this
+        // frame's slots used is now dead weight; let it go rather than
+        // letting the free list grow unbounded across resizes.
+        pool.prune_except(&live_keys);
+
+        Self {
+            slots,
+            edges,
+            order,
+            records: HashMap::new(),
+        }
+    }
+
+    /// Wires the pass that runs for node `name` when [`Renderer::render`]
+    /// replays this graph. Every name in [`RenderGraph::order`] needs one
+    /// before the first render; [`RenderGraph::record`] panics if it's
+    /// missing rather than silently skipping the node.
+    ///
+    /// [`Renderer::render`]: crate::renderer::Renderer::render
+    pub fn set_record(&mut self, name: impl Into<String>, record: Record) {
+        self.records.insert(name.into(), record);
+    }
+
+    /// Runs the node `name`'s wired-in [`Record`] against `encoder`, passing
+    /// `self` and `external` through so it can resolve its slots.
+    pub fn record(&self, name: &str, encoder: &mut wgpu::CommandEncoder, external: &HashMap<&str, &wgpu::TextureView>, label: &str) {
+        let record = self
+            .records
+            .get(name)
+            .unwrap_or_else(|| panic!("render graph node `{name}` has no record wired in"));
+        record(encoder, self, external, label);
+    }
+
+    /// Node names in the order their dependencies are satisfied; every input
+    /// a node reads has already been produced by an earlier name in this
+    /// list.
+    pub fn order(&self) -> &[String] {
+        &self.order
+    }
+
+    /// The consumer node names that read each producer's output, keyed by
+    /// producer name.
+    pub fn edges(&self) -> &HashMap<String, Vec<String>> {
+        &self.edges
+    }
+
+    /// Resolves a slot to the view that should be bound for it. `external`
+    /// supplies views for `Output::External` slots (and any other slot the
+    /// caller wants to override) since those are only known once a frame is
+    /// in flight.
+    pub fn slot_view<'a>(&'a self, name: &str, external: &HashMap<&str, &'a wgpu::TextureView>) -> &'a wgpu::TextureView {
+        if let Some(view) = external.get(name) {
+            return view;
+        }
+        self.slots
+            .get(name)
+            .unwrap_or_else(|| panic!("no such render graph slot: {name}"))
+            .view()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::test_support::test_device;
+
+    fn allocated_node(name: &str, output: &str, width_scale: f32) -> NodeDesc {
+        NodeDesc::new(
+            name,
+            vec![],
+            vec![(
+                output.to_string(),
+                Output::Allocated {
+                    width_scale,
+                    height_scale: 1.0,
+                    format: wgpu::TextureFormat::Rgba8Unorm,
+                    sample_count: 1,
+                },
+            )],
+        )
+    }
+
+    /// A graph built at one resolution, rebuilt against the same pool at a
+    /// different resolution, should prune the first resolution's textures
+    /// out of the pool's free list rather than leaving them to accumulate —
+    /// this is exactly the call chunk0-5 initially dropped.
+    #[test]
+    fn new_prunes_pool_when_resolution_changes() {
+        let Some((device, _queue)) = test_device() else {
+            return;
+        };
+        let pool = TexturePool::new();
+
+        let first = RenderGraph::new(&device, &pool, 64, 64, vec![allocated_node("a", "a_out", 1.0)]);
+        drop(first);
+        assert_eq!(pool.free_keys().len(), 1, "the 64x64 texture should be back in the pool");
+
+        let second = RenderGraph::new(&device, &pool, 128, 128, vec![allocated_node("a", "a_out", 1.0)]);
+        drop(second);
+        let free_keys = pool.free_keys();
+        assert_eq!(free_keys.len(), 1, "the stale 64x64 texture should have been pruned");
+        assert!(free_keys.iter().all(|key| key.width == 128 && key.height == 128));
+    }
+
+    /// A node whose input is never produced by any node's output is treated
+    /// as having no dependency on it — `RenderGraph::new` should build the
+    /// graph rather than hang or panic.
+    #[test]
+    fn new_ignores_inputs_with_no_producer() {
+        let Some((device, _queue)) = test_device() else {
+            return;
+        };
+        let pool = TexturePool::new();
+
+        let node = NodeDesc::new("consumer", vec!["never_produced".to_string()], vec![]);
+        let graph = RenderGraph::new(&device, &pool, 64, 64, vec![node]);
+
+        assert_eq!(graph.order(), &["consumer".to_string()]);
+    }
+
+    /// Two nodes whose outputs feed each other's inputs form a cycle that
+    /// can never be topologically sorted; `RenderGraph::new` must panic
+    /// rather than silently dropping one of them from `order()`.
+    #[test]
+    #[should_panic(expected = "render graph has a cycle")]
+    fn new_panics_on_cyclic_graph() {
+        let Some((device, _queue)) = test_device() else {
+            return;
+        };
+        let pool = TexturePool::new();
+
+        let a = NodeDesc::new("a", vec!["b_out".to_string()], vec![("a_out".to_string(), Output::External)]);
+        let b = NodeDesc::new("b", vec!["a_out".to_string()], vec![("b_out".to_string(), Output::External)]);
+
+        RenderGraph::new(&device, &pool, 64, 64, vec![a, b]);
+    }
+
+    /// `Output::Alias` must point at a slot some earlier node actually
+    /// produced; aliasing an unknown name is a wiring bug in
+    /// `build_render_graph_nodes` and should panic loudly instead of
+    /// resolving to a missing texture at draw time.
+    #[test]
+    #[should_panic(expected = "aliases unknown slot")]
+    fn new_panics_on_alias_to_missing_slot() {
+        let Some((device, _queue)) = test_device() else {
+            return;
+        };
+        let pool = TexturePool::new();
+
+        let node = NodeDesc::new("a", vec![], vec![("a_out".to_string(), Output::Alias("nonexistent".to_string()))]);
+
+        RenderGraph::new(&device, &pool, 64, 64, vec![node]);
+    }
+}
@@ -0,0 +1,241 @@
+use anyhow::{Context, Result};
+
+use crate::window::Size;
+
+/// Where a rendered frame ends up: an on-screen swapchain, or an offscreen
+/// texture that can be read back to CPU memory.
+///
+/// [`crate::renderer::Renderer`] only ever talks to this trait, so the same
+/// pass pipeline can drive a visible window (`SurfaceRenderTarget`) or
+/// headless capture for golden-image tests (`OffscreenRenderTarget`).
+pub trait RenderTarget {
+    fn format(&self) -> wgpu::TextureFormat;
+
+    fn size(&self) -> Size;
+
+    fn resize(&mut self, device: &wgpu::Device, size: Size);
+
+    /// Acquires the texture view to render the composed frame into.
+    fn acquire_frame(&mut self) -> Result<AcquiredFrame>;
+
+    /// Reads the most recently rendered frame back as tightly packed `Rgba8`
+    /// pixels, row-major from the top-left. Only offscreen targets support
+    /// this; the default errors out.
+    fn read_pixels(&self, _device: &wgpu::Device, _queue: &wgpu::Queue) -> Result<Vec<u8>> {
+        anyhow::bail!("this render target does not support reading pixels back")
+    }
+}
+
+/// A texture view to render into, plus however this target wants to finish
+/// up once the frame's commands have been submitted.
+pub struct AcquiredFrame {
+    view: wgpu::TextureView,
+    surface_texture: Option<wgpu::SurfaceTexture>,
+}
+
+impl AcquiredFrame {
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    /// Presents the frame if it came from a swapchain; a no-op for offscreen
+    /// targets.
+    pub fn present(self) {
+        if let Some(surface_texture) = self.surface_texture {
+            surface_texture.present();
+        }
+    }
+}
+
+pub struct SurfaceRenderTarget {
+    surface: wgpu::Surface,
+    format: wgpu::TextureFormat,
+    present_mode: wgpu::PresentMode,
+    size: Size,
+}
+
+impl SurfaceRenderTarget {
+    pub fn new(
+        surface: wgpu::Surface,
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        present_mode: wgpu::PresentMode,
+        size: Size,
+    ) -> Self {
+        let target = Self {
+            surface,
+            format,
+            present_mode,
+            size,
+        };
+        target.configure(device);
+        target
+    }
+
+    fn configure(&self, device: &wgpu::Device) {
+        let Size { width, height } = self.size;
+        self.surface.configure(
+            device,
+            &wgpu::SurfaceConfiguration {
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                format: self.format,
+                width,
+                height,
+                present_mode: self.present_mode,
+            },
+        );
+    }
+}
+
+impl RenderTarget for SurfaceRenderTarget {
+    fn format(&self) -> wgpu::TextureFormat {
+        self.format
+    }
+
+    fn size(&self) -> Size {
+        self.size
+    }
+
+    fn resize(&mut self, device: &wgpu::Device, size: Size) {
+        self.size = size;
+        self.configure(device);
+    }
+
+    fn acquire_frame(&mut self) -> Result<AcquiredFrame> {
+        let surface_texture = self
+            .surface
+            .get_current_texture()
+            .context("Failed to get next surface texture")?;
+        let view = surface_texture.texture.create_view(&Default::default());
+        Ok(AcquiredFrame {
+            view,
+            surface_texture: Some(surface_texture),
+        })
+    }
+}
+
+/// An owned offscreen texture, readable back to CPU memory. Used for
+/// headless capture: automated golden-image tests and rendering frames to
+/// disk without a visible window.
+pub struct OffscreenRenderTarget {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    format: wgpu::TextureFormat,
+    size: Size,
+}
+
+impl OffscreenRenderTarget {
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+    pub fn new(device: &wgpu::Device, size: Size) -> Self {
+        let texture = Self::create_texture(device, size);
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Self {
+            texture,
+            view,
+            format: Self::FORMAT,
+            size,
+        }
+    }
+
+    fn create_texture(device: &wgpu::Device, size: Size) -> wgpu::Texture {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Offscreen Render Target Texture"),
+            size: wgpu::Extent3d {
+                width: size.width,
+                height: size.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        })
+    }
+
+    /// Bytes per row of the readback buffer, padded up to wgpu's required
+    /// `COPY_BYTES_PER_ROW_ALIGNMENT`.
+    fn padded_bytes_per_row(width: u32) -> u32 {
+        let bytes_per_pixel = 4;
+        let unpadded = width * bytes_per_pixel;
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        (unpadded + align - 1) / align * align
+    }
+}
+
+impl RenderTarget for OffscreenRenderTarget {
+    fn format(&self) -> wgpu::TextureFormat {
+        self.format
+    }
+
+    fn size(&self) -> Size {
+        self.size
+    }
+
+    fn resize(&mut self, device: &wgpu::Device, size: Size) {
+        self.texture = Self::create_texture(device, size);
+        self.view = self.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        self.size = size;
+    }
+
+    fn acquire_frame(&mut self) -> Result<AcquiredFrame> {
+        Ok(AcquiredFrame {
+            view: self.view.clone(),
+            surface_texture: None,
+        })
+    }
+
+    fn read_pixels(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<Vec<u8>> {
+        let bytes_per_row = Self::padded_bytes_per_row(self.size.width);
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Offscreen Render Target Readback Buffer"),
+            size: (bytes_per_row * self.size.height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(
+                        std::num::NonZeroU32::new(bytes_per_row).context("empty render target")?,
+                    ),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width: self.size.width,
+                height: self.size.height,
+                depth_or_array_layers: 1,
+            },
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let (tx, rx) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = tx.send(result);
+        });
+        device.poll(wgpu::Maintain::Wait);
+        rx.recv().context("readback buffer map channel closed")??;
+
+        let padded = slice.get_mapped_range();
+        let unpadded_bytes_per_row = (self.size.width * 4) as usize;
+        let mut pixels = Vec::with_capacity(unpadded_bytes_per_row * self.size.height as usize);
+        for row in padded.chunks(bytes_per_row as usize) {
+            pixels.extend_from_slice(&row[..unpadded_bytes_per_row]);
+        }
+
+        Ok(pixels)
+    }
+}
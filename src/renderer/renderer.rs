@@ -1,374 +1,825 @@
+use std::{collections::HashMap, rc::Rc};
+
 use anyhow::{Context, Ok, Result};
 
 use crate::{
     entity::Scene,
     renderer::{
         particle::{ParticleRenderer, ParticleRendererBuilder},
-        postprocessing::{BlurRenderPass, BrightPassRenderPass, ComposeRenderPass},
+        postprocessing::{BrightPassRenderPass, ComposeRenderPass, DownsampleRenderPass, UpsampleRenderPass},
+        profiler::GpuProfiler,
+        render_graph::{NodeDesc, Output, RenderGraph},
+        render_target::{OffscreenRenderTarget, RenderTarget, SurfaceRenderTarget},
+        texture_pool::TexturePool,
     },
     window::{Size, Window},
 };
 
-use super::postprocessing::{AddRenderPass, CopyRenderPass};
+mod profiler;
+mod render_graph;
+mod render_target;
+#[cfg(test)]
+mod test_support;
+mod texture_pool;
 
 const HDR_TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
 const DEPTH_TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
 
+/// Mip levels in the bloom pyramid, not counting the bright-pass target that
+/// feeds the first downsample. Chosen so the smallest level lands around 8px
+/// for a typical 1080p-ish window.
+const BLOOM_MIP_COUNT: u32 = 6;
+
+/// How far the upsample tent filter reaches, in source (half-resolution) mip texels.
+const BLOOM_FILTER_RADIUS: f32 = 1.0;
+
+/// How strongly the bloom is added back in `ComposeRenderPass`.
+const BLOOM_INTENSITY: f32 = 0.04;
+
+/// Multisample counts the particle pass may be built with.
+const VALID_SAMPLE_COUNTS: [u32; 4] = [1, 2, 4, 8];
+
+const DEFAULT_SAMPLE_COUNT: u32 = 1;
+
 pub struct Renderer {
-    surface: wgpu::Surface,
-    surface_format: wgpu::TextureFormat,
+    render_target: Box<dyn RenderTarget>,
     device: wgpu::Device,
     queue: wgpu::Queue,
-    render_targets: RenderTargets,
-    particle_renderer: ParticleRenderer,
-    bright_pass_render_pass: BrightPassRenderPass,
-    bloom_blur_render_pass: BlurRenderPass,
-    bloom_combine_render_pass: AddRenderPass,
-    bloom_blur_render_passes: Vec<BlurRenderPass>,
-    bloom_combine_render_passes: Vec<CopyRenderPass>,
-    compose_render_pass: ComposeRenderPass,
+    sample_count: u32,
+    texture_pool: TexturePool,
+    render_graph: RenderGraph,
+    // `Rc`-wrapped so each pass's `draw` can be shared into the closure
+    // `render_graph` runs it with (see `wire_render_graph_records`) while
+    // `Renderer` still holds its own handle to call `update`/`set_*` on.
+    particle_renderer: Rc<ParticleRenderer>,
+    bright_pass_render_pass: Rc<BrightPassRenderPass>,
+    bloom_downsample_render_passes: Vec<Rc<DownsampleRenderPass>>,
+    bloom_upsample_render_passes: Vec<Rc<UpsampleRenderPass>>,
+    compose_render_pass: Rc<ComposeRenderPass>,
+    bloom_intensity: f32,
+    profiler: Option<GpuProfiler>,
+    last_frame_timings: Vec<(String, f64)>,
 }
 
 impl Renderer {
     pub async fn new(window: &impl Window, scene: &Scene) -> Result<Self> {
-        let instance = wgpu::Instance::new(wgpu::Backends::PRIMARY);
-        let surface = unsafe { instance.create_surface(&window) };
-
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
-                compatible_surface: Some(&surface),
-                force_fallback_adapter: false,
-            })
-            .await
-            .context("No adapter found")?;
-
-        let surface_format = surface
-            .get_preferred_format(&adapter)
-            .context("No preferred format found")?;
-
-        let (device, queue) = adapter
-            .request_device(&wgpu::DeviceDescriptor::default(), None)
-            .await?;
-
-        let Size { width, height } = window.size();
-
-        Self::configure_surface(&surface, &device, surface_format, width, height);
-
-        let render_targets = RenderTargets::new(&device, width, height);
-
-        let particle_renderer = ParticleRendererBuilder::new(scene)
-            .color_target_format(HDR_TEXTURE_FORMAT)
-            .depth_format(DEPTH_TEXTURE_FORMAT)
-            .build(&device);
+        RendererBuilder::new().build(window, scene).await
+    }
 
-        let bright_pass_render_pass =
-            BrightPassRenderPass::new(&device, &render_targets.color, HDR_TEXTURE_FORMAT);
-
-        let bloom_blur_render_pass =
-            BlurRenderPass::new(&device, &render_targets.bright_pass, HDR_TEXTURE_FORMAT);
-
-        let bloom_blur_render_passes = {
-            let all_blur_texture_views_but_last = render_targets
-                .bloom_blur
-                .iter()
-                .take(render_targets.bloom_blur.len() - 1);
-            let src_texture_views =
-                std::iter::once(&render_targets.bright_pass).chain(all_blur_texture_views_but_last);
-
-            src_texture_views
-                .map(|src_texture_view| {
-                    BlurRenderPass::new(&device, src_texture_view, HDR_TEXTURE_FORMAT)
-                })
-                .collect::<Vec<_>>()
-        };
+    /// Creates a `Renderer` that renders into an owned offscreen texture
+    /// instead of a window's swapchain. Pair with [`Renderer::capture_frame`]
+    /// to render frames to disk or drive golden-image tests without a
+    /// visible window.
+    pub async fn new_offscreen(size: Size, scene: &Scene) -> Result<Self> {
+        RendererBuilder::new().build_offscreen(size, scene).await
+    }
 
-        let bloom_combine_render_pass = AddRenderPass::new(
+    fn new_with_render_target(
+        render_target: Box<dyn RenderTarget>,
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        sample_count: u32,
+        scene: &Scene,
+    ) -> Result<Self> {
+        let Size { width, height } = render_target.size();
+
+        let texture_pool = TexturePool::new();
+        let mut render_graph = RenderGraph::new(
             &device,
-            &[&render_targets.bloom_blur[0], &render_targets.bloom_blur[1]],
-            HDR_TEXTURE_FORMAT,
+            &texture_pool,
+            width,
+            height,
+            Self::build_render_graph_nodes(sample_count),
         );
 
-        let bloom_combine_render_passes = render_targets
-            .bloom_blur
-            .iter()
-            .map(|texture_view| CopyRenderPass::new(&device, texture_view, HDR_TEXTURE_FORMAT))
-            .collect::<Vec<_>>();
+        let particle_renderer = Rc::new(
+            ParticleRendererBuilder::new(scene)
+                .color_target_format(HDR_TEXTURE_FORMAT)
+                .depth_format(DEPTH_TEXTURE_FORMAT)
+                .sample_count(sample_count)
+                .build(&device),
+        );
 
-        let compose_render_pass = ComposeRenderPass::new(
+        let bright_pass_render_pass =
+            Rc::new(Self::create_bright_pass_render_pass(&device, &render_graph, sample_count));
+        let bloom_downsample_render_passes: Vec<_> = Self::create_bloom_downsample_render_passes(&device, &render_graph)
+            .into_iter()
+            .map(Rc::new)
+            .collect();
+        let bloom_upsample_render_passes: Vec<_> = Self::create_bloom_upsample_render_passes(&device, &render_graph)
+            .into_iter()
+            .map(Rc::new)
+            .collect();
+        let compose_render_pass = Rc::new(Self::create_compose_render_pass(
             &device,
-            &render_targets.color,
-            &render_targets.bloom,
-            surface_format,
+            &render_graph,
+            sample_count,
+            render_target.format(),
+        ));
+
+        Self::wire_render_graph_records(
+            &mut render_graph,
+            sample_count,
+            &particle_renderer,
+            &bright_pass_render_pass,
+            &bloom_downsample_render_passes,
+            &bloom_upsample_render_passes,
+            &compose_render_pass,
         );
 
+        let profiler = GpuProfiler::new(&device, &queue);
+
         Ok(Self {
-            surface,
-            surface_format,
+            render_target,
             device,
             queue,
-            render_targets,
+            sample_count,
+            texture_pool,
+            render_graph,
             particle_renderer,
             bright_pass_render_pass,
-            bloom_blur_render_pass,
-            bloom_combine_render_pass,
-            bloom_blur_render_passes,
-            bloom_combine_render_passes,
+            bloom_downsample_render_passes,
+            bloom_upsample_render_passes,
             compose_render_pass,
+            bloom_intensity: BLOOM_INTENSITY,
+            profiler,
+            last_frame_timings: Vec::new(),
         })
     }
 
-    fn configure_surface(
-        surface: &wgpu::Surface,
+    /// Sets how strongly the bloom pyramid is added back into the composed
+    /// image. Takes effect on the next call to [`Renderer::render`].
+    pub fn set_bloom_intensity(&mut self, bloom_intensity: f32) {
+        self.bloom_intensity = bloom_intensity;
+    }
+
+    /// Per-pass GPU durations from the most recently rendered frame, in
+    /// milliseconds. Empty if the adapter doesn't support
+    /// `wgpu::Features::TIMESTAMP_QUERY`.
+    pub fn last_frame_timings(&self) -> Vec<(String, f64)> {
+        self.last_frame_timings.clone()
+    }
+
+    /// Declares the render graph for the particle, bright-pass, bloom, and
+    /// compose stages: a `color`/`depth` (and, when multisampled,
+    /// `color_resolve`) slot from the particle pass, a quarter-resolution
+    /// `bright_pass` slot, a `bloom_mip_N` pyramid that halves in resolution
+    /// each level, and a `frame` slot bound by the caller at render time.
+    ///
+    /// The upsample chain blends back into the same `bloom_mip_N` textures
+    /// the downsample chain wrote, so each upsample node's output is an
+    /// `Output::Alias` of its mip under a `_upsampled` name, giving it a name
+    /// distinct from the downsample write without allocating a second
+    /// texture for it.
+    fn build_render_graph_nodes(sample_count: u32) -> Vec<NodeDesc> {
+        let mut nodes = Vec::new();
+
+        let mut particle_outputs = vec![
+            (
+                "color".to_string(),
+                Output::Allocated {
+                    width_scale: 1.0,
+                    height_scale: 1.0,
+                    format: HDR_TEXTURE_FORMAT,
+                    sample_count,
+                },
+            ),
+            (
+                "depth".to_string(),
+                Output::Allocated {
+                    width_scale: 1.0,
+                    height_scale: 1.0,
+                    format: DEPTH_TEXTURE_FORMAT,
+                    sample_count,
+                },
+            ),
+        ];
+        if sample_count > 1 {
+            particle_outputs.push((
+                "color_resolve".to_string(),
+                Output::Allocated {
+                    width_scale: 1.0,
+                    height_scale: 1.0,
+                    format: HDR_TEXTURE_FORMAT,
+                    sample_count: 1,
+                },
+            ));
+        }
+        nodes.push(NodeDesc::new("particle", vec![], particle_outputs));
+
+        let color_source = Self::color_source_name(sample_count).to_string();
+        nodes.push(NodeDesc::new(
+            "bright_pass",
+            vec![color_source.clone()],
+            vec![(
+                "bright_pass".to_string(),
+                Output::Allocated {
+                    width_scale: 0.25,
+                    height_scale: 0.25,
+                    format: HDR_TEXTURE_FORMAT,
+                    sample_count: 1,
+                },
+            )],
+        ));
+
+        for i in 0..BLOOM_MIP_COUNT as usize {
+            let input = if i == 0 {
+                "bright_pass".to_string()
+            } else {
+                Self::bloom_mip_name(i - 1)
+            };
+            // `bright_pass` is a quarter of the surface; each mip halves that again.
+            let scale = 0.25 / 2f32.powi(i as i32 + 1);
+            nodes.push(NodeDesc::new(
+                format!("bloom_downsample_{i}"),
+                vec![input],
+                vec![(
+                    Self::bloom_mip_name(i),
+                    Output::Allocated {
+                        width_scale: scale,
+                        height_scale: scale,
+                        format: HDR_TEXTURE_FORMAT,
+                        sample_count: 1,
+                    },
+                )],
+            ));
+        }
+
+        let upsample_count = BLOOM_MIP_COUNT as usize - 1;
+        for i in 0..upsample_count {
+            let dst_mip = upsample_count - 1 - i;
+            let src_mip = dst_mip + 1;
+            let input = if i == 0 {
+                Self::bloom_mip_name(src_mip)
+            } else {
+                Self::bloom_mip_upsampled_name(src_mip)
+            };
+            nodes.push(NodeDesc::new(
+                format!("bloom_upsample_{i}"),
+                vec![input],
+                vec![(
+                    Self::bloom_mip_upsampled_name(dst_mip),
+                    Output::Alias(Self::bloom_mip_name(dst_mip)),
+                )],
+            ));
+        }
+
+        nodes.push(NodeDesc::new(
+            "compose",
+            vec![color_source, Self::final_bloom_name()],
+            vec![("frame".to_string(), Output::External)],
+        ));
+
+        nodes
+    }
+
+    /// The color slot everything downstream of the particle pass should read:
+    /// the resolve target when multisampled, `color` otherwise.
+    fn color_source_name(sample_count: u32) -> &'static str {
+        if sample_count > 1 {
+            "color_resolve"
+        } else {
+            "color"
+        }
+    }
+
+    fn bloom_mip_name(i: usize) -> String {
+        format!("bloom_mip_{i}")
+    }
+
+    fn bloom_mip_upsampled_name(i: usize) -> String {
+        format!("bloom_mip_{i}_upsampled")
+    }
+
+    /// The slot holding the final composited bloom, after the upsample chain
+    /// (if any levels exist to upsample) has run.
+    fn final_bloom_name() -> String {
+        if BLOOM_MIP_COUNT as usize > 1 {
+            Self::bloom_mip_upsampled_name(0)
+        } else {
+            Self::bloom_mip_name(0)
+        }
+    }
+
+    fn create_bright_pass_render_pass(
         device: &wgpu::Device,
-        format: wgpu::TextureFormat,
-        width: u32,
-        height: u32,
-    ) {
-        surface.configure(
+        render_graph: &RenderGraph,
+        sample_count: u32,
+    ) -> BrightPassRenderPass {
+        let no_external = HashMap::new();
+        BrightPassRenderPass::new(
             device,
-            &wgpu::SurfaceConfiguration {
-                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-                format,
-                width,
-                height,
-                present_mode: wgpu::PresentMode::Fifo,
-            },
+            render_graph.slot_view(Self::color_source_name(sample_count), &no_external),
+            HDR_TEXTURE_FORMAT,
+        )
+    }
+
+    /// Builds the downsample chain: `bright_pass -> mips[0] -> mips[1] -> ...`.
+    /// Only the first pass (reading directly from the bright pass) uses Karis
+    /// averaging, since that's the only level still holding unclamped highlights.
+    fn create_bloom_downsample_render_passes(
+        device: &wgpu::Device,
+        render_graph: &RenderGraph,
+    ) -> Vec<DownsampleRenderPass> {
+        let no_external = HashMap::new();
+        (0..BLOOM_MIP_COUNT as usize)
+            .map(|i| {
+                let src_name = if i == 0 {
+                    "bright_pass".to_string()
+                } else {
+                    Self::bloom_mip_name(i - 1)
+                };
+                DownsampleRenderPass::new(
+                    device,
+                    render_graph.slot_view(&src_name, &no_external),
+                    HDR_TEXTURE_FORMAT,
+                    i == 0,
+                )
+            })
+            .collect()
+    }
+
+    /// Builds the upsample chain: `mips[last] -> mips[last - 1] -> ... -> mips[0]`,
+    /// each pass additively blending its tent-filtered source into the next
+    /// larger level.
+    fn create_bloom_upsample_render_passes(device: &wgpu::Device, render_graph: &RenderGraph) -> Vec<UpsampleRenderPass> {
+        let no_external = HashMap::new();
+        let upsample_count = BLOOM_MIP_COUNT as usize - 1;
+        (0..upsample_count)
+            .map(|i| {
+                let dst_mip = upsample_count - 1 - i;
+                let src_mip = dst_mip + 1;
+                let src_name = if i == 0 {
+                    Self::bloom_mip_name(src_mip)
+                } else {
+                    Self::bloom_mip_upsampled_name(src_mip)
+                };
+                UpsampleRenderPass::new(device, render_graph.slot_view(&src_name, &no_external), HDR_TEXTURE_FORMAT)
+            })
+            .collect()
+    }
+
+    fn create_compose_render_pass(
+        device: &wgpu::Device,
+        render_graph: &RenderGraph,
+        sample_count: u32,
+        dst_format: wgpu::TextureFormat,
+    ) -> ComposeRenderPass {
+        let no_external = HashMap::new();
+        ComposeRenderPass::new(
+            device,
+            render_graph.slot_view(Self::color_source_name(sample_count), &no_external),
+            render_graph.slot_view(&Self::final_bloom_name(), &no_external),
+            dst_format,
         )
     }
 
     pub fn resize(&mut self, size: Size) {
         let Size { width, height } = size;
-        Self::configure_surface(
-            &self.surface,
+        self.render_target.resize(&self.device, size);
+        self.render_graph = RenderGraph::new(
             &self.device,
-            self.surface_format,
+            &self.texture_pool,
             width,
             height,
+            Self::build_render_graph_nodes(self.sample_count),
         );
-        self.render_targets = RenderTargets::new(&self.device, width, height);
-        self.bright_pass_render_pass =
-            BrightPassRenderPass::new(&self.device, &self.render_targets.color, HDR_TEXTURE_FORMAT);
-        self.bloom_blur_render_pass = BlurRenderPass::new(
+        self.bright_pass_render_pass = Rc::new(Self::create_bright_pass_render_pass(
             &self.device,
-            &self.render_targets.bright_pass,
-            HDR_TEXTURE_FORMAT,
-        );
-        self.compose_render_pass = ComposeRenderPass::new(
+            &self.render_graph,
+            self.sample_count,
+        ));
+        self.bloom_downsample_render_passes = Self::create_bloom_downsample_render_passes(&self.device, &self.render_graph)
+            .into_iter()
+            .map(Rc::new)
+            .collect();
+        self.bloom_upsample_render_passes = Self::create_bloom_upsample_render_passes(&self.device, &self.render_graph)
+            .into_iter()
+            .map(Rc::new)
+            .collect();
+        self.compose_render_pass = Rc::new(Self::create_compose_render_pass(
             &self.device,
-            &self.render_targets.color,
-            &self.render_targets.bloom,
-            self.surface_format,
+            &self.render_graph,
+            self.sample_count,
+            self.render_target.format(),
+        ));
+
+        Self::wire_render_graph_records(
+            &mut self.render_graph,
+            self.sample_count,
+            &self.particle_renderer,
+            &self.bright_pass_render_pass,
+            &self.bloom_downsample_render_passes,
+            &self.bloom_upsample_render_passes,
+            &self.compose_render_pass,
         );
     }
 
-    pub fn render(&mut self, scene: &Scene) {
+    /// Wires every `build_render_graph_nodes` node's [`Record`] into
+    /// `render_graph`, so [`Renderer::render`] can replay `order()` without
+    /// knowing what any individual node draws. Each pass is captured by its
+    /// `Rc` clone (not a borrow of `self`) since the closures end up stored
+    /// inside `render_graph`, itself a field of `Renderer`.
+    ///
+    /// [`Record`]: crate::renderer::render_graph::Record
+    fn wire_render_graph_records(
+        render_graph: &mut RenderGraph,
+        sample_count: u32,
+        particle_renderer: &Rc<ParticleRenderer>,
+        bright_pass_render_pass: &Rc<BrightPassRenderPass>,
+        bloom_downsample_render_passes: &[Rc<DownsampleRenderPass>],
+        bloom_upsample_render_passes: &[Rc<UpsampleRenderPass>],
+        compose_render_pass: &Rc<ComposeRenderPass>,
+    ) {
+        let particle_renderer = particle_renderer.clone();
+        render_graph.set_record(
+            "particle",
+            Box::new(move |encoder, graph, external, label| {
+                let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some(label),
+                    color_attachments: &[wgpu::RenderPassColorAttachment {
+                        view: graph.slot_view("color", external),
+                        resolve_target: (sample_count > 1).then(|| graph.slot_view("color_resolve", external)),
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                            store: true,
+                        },
+                    }],
+                    depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                        view: graph.slot_view("depth", external),
+                        depth_ops: Some(wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(1.0),
+                            store: false,
+                        }),
+                        stencil_ops: None,
+                    }),
+                });
+                particle_renderer.draw(&mut rpass);
+            }),
+        );
+
+        let bright_pass_render_pass = bright_pass_render_pass.clone();
+        render_graph.set_record(
+            "bright_pass",
+            Box::new(move |encoder, graph, external, label| {
+                let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some(label),
+                    color_attachments: &[wgpu::RenderPassColorAttachment {
+                        view: graph.slot_view("bright_pass", external),
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                            store: true,
+                        },
+                    }],
+                    depth_stencil_attachment: None,
+                });
+                bright_pass_render_pass.draw(&mut rpass);
+            }),
+        );
+
+        for (i, pass) in bloom_downsample_render_passes.iter().enumerate() {
+            let pass = pass.clone();
+            let mip_name = Self::bloom_mip_name(i);
+            render_graph.set_record(
+                format!("bloom_downsample_{i}"),
+                Box::new(move |encoder, graph, external, label| {
+                    let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                        label: Some(label),
+                        color_attachments: &[wgpu::RenderPassColorAttachment {
+                            view: graph.slot_view(&mip_name, external),
+                            resolve_target: None,
+                            ops: wgpu::Operations {
+                                load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                                store: true,
+                            },
+                        }],
+                        depth_stencil_attachment: None,
+                    });
+                    pass.draw(&mut rpass);
+                }),
+            );
+        }
+
+        let upsample_count = bloom_upsample_render_passes.len();
+        for (i, pass) in bloom_upsample_render_passes.iter().enumerate() {
+            let pass = pass.clone();
+            let dst_mip_name = Self::bloom_mip_name(upsample_count - 1 - i);
+            render_graph.set_record(
+                format!("bloom_upsample_{i}"),
+                Box::new(move |encoder, graph, external, label| {
+                    let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                        label: Some(label),
+                        color_attachments: &[wgpu::RenderPassColorAttachment {
+                            view: graph.slot_view(&dst_mip_name, external),
+                            resolve_target: None,
+                            ops: wgpu::Operations {
+                                load: wgpu::LoadOp::Load,
+                                store: true,
+                            },
+                        }],
+                        depth_stencil_attachment: None,
+                    });
+                    pass.draw(&mut rpass);
+                }),
+            );
+        }
+
+        let compose_render_pass = compose_render_pass.clone();
+        render_graph.set_record(
+            "compose",
+            Box::new(move |encoder, graph, external, label| {
+                let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some(label),
+                    color_attachments: &[wgpu::RenderPassColorAttachment {
+                        view: graph.slot_view("frame", external),
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                            store: true,
+                        },
+                    }],
+                    depth_stencil_attachment: None,
+                });
+                compose_render_pass.draw(&mut rpass);
+            }),
+        );
+    }
+
+    pub fn render(&mut self, scene: &Scene) -> Result<()> {
         self.particle_renderer.update(&self.queue, scene);
         self.bright_pass_render_pass.update(&self.queue, scene);
         self.compose_render_pass.update(&self.queue, scene);
+        self.compose_render_pass
+            .set_bloom_intensity(&self.queue, self.bloom_intensity);
+        for upsample_render_pass in &self.bloom_upsample_render_passes {
+            upsample_render_pass.set_filter_radius(&self.queue, BLOOM_FILTER_RADIUS);
+        }
+
+        // Acquired up front (rather than only once the `compose` node comes
+        // up) so every node's record can take the same `external` map; the
+        // swapchain frame only needs to exist by the time we submit.
+        let frame = self.render_target.acquire_frame()?;
+        let mut external: HashMap<&str, &wgpu::TextureView> = HashMap::new();
+        external.insert("frame", frame.view());
 
         let mut encoder = self
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
 
-        {
-            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Particle Render Pass"),
-                color_attachments: &[wgpu::RenderPassColorAttachment {
-                    view: &self.render_targets.color,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
-                        store: true,
-                    },
-                }],
-                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                    view: &self.render_targets.depth,
-                    depth_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(1.0),
-                        store: false,
-                    }),
-                    stencil_ops: None,
-                }),
-            });
-            self.particle_renderer.draw(&mut rpass);
-        }
+        for name in self.render_graph.order().to_vec() {
+            let label = Self::render_graph_node_label(&name);
+            if let Some(profiler) = self.profiler.as_mut() {
+                profiler.begin_pass(&mut encoder, label.clone());
+            }
 
-        {
-            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Bright Pass Render Pass"),
-                color_attachments: &[wgpu::RenderPassColorAttachment {
-                    view: &self.render_targets.bright_pass,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
-                        store: true,
-                    },
-                }],
-                depth_stencil_attachment: None,
-            });
-            self.bright_pass_render_pass.draw(&mut rpass);
+            self.render_graph.record(&name, &mut encoder, &external, &label);
+
+            if let Some(profiler) = self.profiler.as_mut() {
+                profiler.end_pass(&mut encoder);
+            }
         }
 
-        // {
-        //     let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-        //         label: Some("Bloom Blur Render Pass"),
-        //         color_attachments: &[wgpu::RenderPassColorAttachment {
-        //             view: &self.render_targets.bloom_blur[0],
-        //             resolve_target: None,
-        //             ops: wgpu::Operations {
-        //                 load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
-        //                 store: true,
-        //             },
-        //         }],
-        //         depth_stencil_attachment: None,
-        //     });
-        //     self.bloom_blur_render_pass.draw(&mut rpass);
-        // }
-
-        for i in 0..self.render_targets.bloom_blur.len() {
-            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some(format!("Bloom Blur Render Pass {}", i).as_str()),
-                color_attachments: &[wgpu::RenderPassColorAttachment {
-                    view: &self.render_targets.bloom_blur[i],
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
-                        store: true,
-                    },
-                }],
-                depth_stencil_attachment: None,
-            });
-            self.bloom_blur_render_passes[i].draw(&mut rpass);
+        self.queue.submit(std::iter::once(encoder.finish()));
+        frame.present();
+
+        if let Some(mut profiler) = self.profiler.take() {
+            self.last_frame_timings = profiler.resolve(&self.device, &self.queue);
+            self.profiler = Some(profiler);
         }
 
-        {
-            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Bloom Combine Render Pass"),
-                color_attachments: &[wgpu::RenderPassColorAttachment {
-                    view: &self.render_targets.bloom,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
-                        store: true,
-                    },
-                }],
-                depth_stencil_attachment: None,
-            });
-            // self.bloom_combine_render_pass.draw(&mut rpass);
-            for render_pass in &self.bloom_combine_render_passes {
-                render_pass.draw(&mut rpass);
+        Ok(())
+    }
+
+    /// Human-readable label for a render graph node, used both as the
+    /// render pass's debug label and as the name reported in
+    /// [`Renderer::last_frame_timings`].
+    fn render_graph_node_label(name: &str) -> String {
+        if let Some(i) = name.strip_prefix("bloom_downsample_") {
+            format!("Bloom Downsample Render Pass {i}")
+        } else if let Some(i) = name.strip_prefix("bloom_upsample_") {
+            format!("Bloom Upsample Render Pass {i}")
+        } else {
+            match name {
+                "particle" => "Particle Render Pass".to_string(),
+                "bright_pass" => "Bright Pass Render Pass".to_string(),
+                "compose" => "Compose Render Pass".to_string(),
+                _ => name.to_string(),
             }
         }
+    }
 
-        let surface_texture = self
-            .surface
-            .get_current_texture()
-            .expect("Failed to get next surface texture");
-
-        let surface_texture_view = surface_texture.texture.create_view(&Default::default());
-
-        {
-            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Compose Render Pass"),
-                color_attachments: &[wgpu::RenderPassColorAttachment {
-                    view: &surface_texture_view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
-                        store: true,
-                    },
-                }],
-                depth_stencil_attachment: None,
-            });
-            self.compose_render_pass.draw(&mut rpass);
+    /// Renders one frame and returns the composed image as tightly packed
+    /// `Rgba8` pixels. Only available on a `Renderer` built with
+    /// [`Renderer::new_offscreen`].
+    pub fn capture_frame(&mut self, scene: &Scene) -> Result<Vec<u8>> {
+        self.render(scene)?;
+        self.render_target.read_pixels(&self.device, &self.queue)
+    }
+}
+
+/// Builds a [`Renderer`] with non-default options. `RendererBuilder::new()`
+/// matches [`Renderer::new`]'s defaults; chain setters before calling
+/// [`RendererBuilder::build`] or [`RendererBuilder::build_offscreen`].
+pub struct RendererBuilder {
+    sample_count: u32,
+    present_mode: wgpu::PresentMode,
+    power_preference: wgpu::PowerPreference,
+    force_fallback_adapter: bool,
+}
+
+impl RendererBuilder {
+    pub fn new() -> Self {
+        Self {
+            sample_count: DEFAULT_SAMPLE_COUNT,
+            present_mode: wgpu::PresentMode::Fifo,
+            power_preference: wgpu::PowerPreference::default(),
+            force_fallback_adapter: false,
         }
+    }
 
-        self.queue.submit(std::iter::once(encoder.finish()));
+    /// Multisample count for the particle pass's color and depth targets.
+    /// Must be one of 1, 2, 4, or 8; [`RendererBuilder::build`] and
+    /// [`RendererBuilder::build_offscreen`] fail if the adapter doesn't
+    /// support it for `HDR_TEXTURE_FORMAT` or `DEPTH_TEXTURE_FORMAT`.
+    pub fn sample_count(mut self, sample_count: u32) -> Self {
+        self.sample_count = sample_count;
+        self
+    }
 
-        surface_texture.present();
+    /// How the swapchain paces presentation, e.g. `Mailbox` for unthrottled,
+    /// low-latency presentation. Only meaningful for [`RendererBuilder::build`];
+    /// ignored by [`RendererBuilder::build_offscreen`], which has no
+    /// swapchain to present to. Falls back to `Fifo` with a logged warning
+    /// if the surface doesn't support it.
+    pub fn present_mode(mut self, present_mode: wgpu::PresentMode) -> Self {
+        self.present_mode = present_mode;
+        self
     }
-}
 
-struct RenderTargets {
-    color: wgpu::TextureView,
-    depth: wgpu::TextureView,
-    bright_pass: wgpu::TextureView,
-    bloom_blur: Vec<wgpu::TextureView>,
-    bloom: wgpu::TextureView,
-}
+    /// Steers adapter selection towards a discrete or integrated GPU.
+    pub fn power_preference(mut self, power_preference: wgpu::PowerPreference) -> Self {
+        self.power_preference = power_preference;
+        self
+    }
 
-impl RenderTargets {
-    fn new(device: &wgpu::Device, width: u32, height: u32) -> Self {
-        let color = Self::create_render_target_texture_view(
-            device,
-            "Color Texture",
-            width,
-            height,
-            HDR_TEXTURE_FORMAT,
-        );
-        let depth = Self::create_render_target_texture_view(
-            device,
-            "Depth Texture",
-            width,
-            height,
-            DEPTH_TEXTURE_FORMAT,
-        );
-        let bright_pass = Self::create_render_target_texture_view(
-            device,
-            "Bright Pass Texture",
-            width / 4,
-            height / 4,
-            HDR_TEXTURE_FORMAT,
-        );
-        let bloom_blur = (0..16)
-            .map(|i| {
-                Self::create_render_target_texture_view(
-                    device,
-                    format!("Blur Texture {}", i).as_str(),
-                    width / 4,
-                    height / 4,
-                    HDR_TEXTURE_FORMAT,
-                )
+    /// Forces selection of a software/fallback adapter, for environments
+    /// without a usable GPU.
+    pub fn force_fallback_adapter(mut self, force_fallback_adapter: bool) -> Self {
+        self.force_fallback_adapter = force_fallback_adapter;
+        self
+    }
+
+    pub async fn build(self, window: &impl Window, scene: &Scene) -> Result<Renderer> {
+        let instance = wgpu::Instance::new(wgpu::Backends::PRIMARY);
+        let surface = unsafe { instance.create_surface(&window) };
+
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: self.power_preference,
+                compatible_surface: Some(&surface),
+                force_fallback_adapter: self.force_fallback_adapter,
             })
-            .collect::<Vec<_>>();
-        let bloom = Self::create_render_target_texture_view(
-            device,
-            "Bloom Texture",
-            width / 4,
-            height / 4,
-            HDR_TEXTURE_FORMAT,
+            .await
+            .context("No adapter found")?;
+
+        Self::validate_sample_count(&adapter, self.sample_count)?;
+
+        let surface_format = surface
+            .get_preferred_format(&adapter)
+            .context("No preferred format found")?;
+
+        let present_mode = Self::resolve_present_mode(&surface, &adapter, self.present_mode);
+
+        let (device, queue) = adapter
+            .request_device(&Self::device_descriptor(&adapter), None)
+            .await?;
+
+        let size = window.size();
+
+        let render_target = SurfaceRenderTarget::new(surface, &device, surface_format, present_mode, size);
+
+        Renderer::new_with_render_target(Box::new(render_target), device, queue, self.sample_count, scene)
+    }
+
+    pub async fn build_offscreen(self, size: Size, scene: &Scene) -> Result<Renderer> {
+        let instance = wgpu::Instance::new(wgpu::Backends::PRIMARY);
+
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: self.power_preference,
+                compatible_surface: None,
+                force_fallback_adapter: self.force_fallback_adapter,
+            })
+            .await
+            .context("No adapter found")?;
+
+        Self::validate_sample_count(&adapter, self.sample_count)?;
+
+        let (device, queue) = adapter
+            .request_device(&Self::device_descriptor(&adapter), None)
+            .await?;
+
+        let render_target = OffscreenRenderTarget::new(&device, size);
+
+        Renderer::new_with_render_target(Box::new(render_target), device, queue, self.sample_count, scene)
+    }
+
+    /// Falls back to `Fifo` with a logged warning if `present_mode` isn't
+    /// among the surface's supported modes for `adapter`; every surface is
+    /// required to support `Fifo`, so the fallback is always valid.
+    fn resolve_present_mode(
+        surface: &wgpu::Surface,
+        adapter: &wgpu::Adapter,
+        present_mode: wgpu::PresentMode,
+    ) -> wgpu::PresentMode {
+        let supported = surface.get_supported_modes(adapter);
+        if supported.contains(&present_mode) {
+            return present_mode;
+        }
+
+        log::warn!(
+            "present mode {:?} is not supported by this surface, falling back to {:?}",
+            present_mode,
+            wgpu::PresentMode::Fifo
         );
+        wgpu::PresentMode::Fifo
+    }
 
-        Self {
-            color,
-            depth,
-            bright_pass,
-            bloom_blur,
-            bloom,
+    /// Requests `Features::TIMESTAMP_QUERY` when the adapter supports it, so
+    /// [`GpuProfiler::new`] can allocate its query set; leaves every other
+    /// feature and limit at its default.
+    fn device_descriptor(adapter: &wgpu::Adapter) -> wgpu::DeviceDescriptor<'static> {
+        wgpu::DeviceDescriptor {
+            label: None,
+            features: adapter.features() & wgpu::Features::TIMESTAMP_QUERY,
+            limits: wgpu::Limits::default(),
         }
     }
 
-    fn create_render_target_texture_view(
-        device: &wgpu::Device,
-        label: &str,
-        width: u32,
-        height: u32,
-        format: wgpu::TextureFormat,
-    ) -> wgpu::TextureView {
-        let texture = device.create_texture(&wgpu::TextureDescriptor {
-            label: Some(label),
-            size: wgpu::Extent3d {
-                width,
-                height,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
-        });
-        texture.create_view(&wgpu::TextureViewDescriptor::default())
+    /// Checks `sample_count` against every format the particle pass
+    /// allocates at that sample count (`build_render_graph_nodes`'s `color`
+    /// and `depth` outputs), not just the color target — an adapter can
+    /// support a given MSAA count for one format and not the other.
+    fn validate_sample_count(adapter: &wgpu::Adapter, sample_count: u32) -> Result<()> {
+        anyhow::ensure!(
+            VALID_SAMPLE_COUNTS.contains(&sample_count),
+            "sample_count must be one of {:?}, got {}",
+            VALID_SAMPLE_COUNTS,
+            sample_count
+        );
+
+        for format in [HDR_TEXTURE_FORMAT, DEPTH_TEXTURE_FORMAT] {
+            let supported = adapter.get_texture_format_features(format).flags.sample_count_supported(sample_count);
+            anyhow::ensure!(supported, "adapter does not support {}x MSAA for {:?}", sample_count, format);
+        }
+
+        Ok(())
     }
-}
\ No newline at end of file
+}
+
+impl Default for RendererBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `OffscreenRenderTarget` exists solely to drive headless rendering, so
+    /// at minimum a captured frame should come back as one `Rgba8` pixel per
+    /// texel with no partially-read rows.
+    ///
+    /// The width is chosen so `width * 4` (100 * 4 = 400) is *not* a multiple
+    /// of `COPY_BYTES_PER_ROW_ALIGNMENT` (256) — a width like 64 pads to
+    /// exactly the unpadded row size and would let a regression that copies
+    /// the padded row verbatim slip past a length-only assertion.
+    #[test]
+    fn capture_frame_returns_tightly_packed_rgba8() {
+        let size = Size { width: 100, height: 48 };
+        let scene = Scene::default();
+
+        let mut renderer = match pollster::block_on(Renderer::new_offscreen(size, &scene)) {
+            Ok(renderer) => renderer,
+            // No adapter available in this environment (e.g. a headless CI
+            // runner without a software rasterizer); nothing to assert.
+            Err(_) => return,
+        };
+
+        let pixels = renderer.capture_frame(&scene).expect("capture_frame should succeed");
+
+        assert_eq!(pixels.len(), size.width as usize * size.height as usize * 4);
+
+        // An empty scene renders no geometry, so every pass clears to the
+        // same value and the composed frame is a single uniform color.
+        // Leaked padding bytes (uninitialized readback buffer memory) would
+        // show up as a non-uniform frame, which a length check alone can't
+        // catch.
+        let first_pixel = &pixels[..4];
+        for pixel in pixels.chunks_exact(4) {
+            assert_eq!(pixel, first_pixel, "expected a uniform frame for an empty scene");
+        }
+    }
+}
@@ -0,0 +1,20 @@
+//! Shared helpers for `#[cfg(test)]` modules under `src/renderer/`.
+
+/// Requests a headless device for tests that need to exercise real
+/// `wgpu::Texture`/`wgpu::Buffer` allocations, or `None` in an environment
+/// without a usable adapter (e.g. a headless CI runner without a software
+/// rasterizer). Callers should return early from the test when this is
+/// `None` rather than asserting anything.
+pub(crate) fn test_device() -> Option<(wgpu::Device, wgpu::Queue)> {
+    pollster::block_on(async {
+        let instance = wgpu::Instance::new(wgpu::Backends::PRIMARY);
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::default(),
+                compatible_surface: None,
+                force_fallback_adapter: false,
+            })
+            .await?;
+        adapter.request_device(&wgpu::DeviceDescriptor::default(), None).await.ok()
+    })
+}
@@ -0,0 +1,212 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct TextureKey {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) format: wgpu::TextureFormat,
+    pub(crate) usage: wgpu::TextureUsages,
+    pub(crate) sample_count: u32,
+}
+
+#[derive(Default)]
+struct TexturePoolInner {
+    free: HashMap<TextureKey, Vec<wgpu::Texture>>,
+}
+
+/// Recycles `wgpu::Texture` allocations keyed by `(width, height, format,
+/// usage, sample_count)`.
+///
+/// `RenderGraph` pulls its render targets from a shared pool instead of
+/// creating them directly, so a resize that briefly revisits a size it's
+/// already seen (e.g. a live window drag) reuses the existing GPU allocation
+/// instead of churning a fresh one every frame. Textures whose size no
+/// longer matches anything currently in use are not freed immediately —
+/// they sit in the pool until [`TexturePool::prune_except`] is told they're
+/// no longer wanted, which `RenderGraph::new` calls once the new set of
+/// targets has been acquired.
+#[derive(Clone, Default)]
+pub struct TexturePool {
+    inner: Arc<Mutex<TexturePoolInner>>,
+}
+
+impl TexturePool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn acquire(
+        &self,
+        device: &wgpu::Device,
+        label: &str,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        usage: wgpu::TextureUsages,
+        sample_count: u32,
+    ) -> PooledTexture {
+        let key = TextureKey {
+            width,
+            height,
+            format,
+            usage,
+            sample_count,
+        };
+
+        let texture = self
+            .inner
+            .lock()
+            .unwrap()
+            .free
+            .get_mut(&key)
+            .and_then(Vec::pop)
+            .unwrap_or_else(|| {
+                device.create_texture(&wgpu::TextureDescriptor {
+                    label: Some(label),
+                    size: wgpu::Extent3d {
+                        width,
+                        height,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count,
+                    dimension: wgpu::TextureDimension::D2,
+                    format,
+                    usage,
+                })
+            });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        PooledTexture {
+            texture: Some(texture),
+            view,
+            key,
+            pool: self.inner.clone(),
+        }
+    }
+
+    /// Drops every pooled texture whose full key isn't in `live_keys`,
+    /// freeing the GPU memory held by targets from a previous, now-abandoned
+    /// resize. Compares every field of the key, not just size: a texture
+    /// whose size matches something still in use but whose format, usage, or
+    /// sample count doesn't match any live request would otherwise sit in
+    /// the free list forever.
+    pub(crate) fn prune_except(&self, live_keys: &[TextureKey]) {
+        self.inner.lock().unwrap().free.retain(|key, _| live_keys.contains(key));
+    }
+
+    /// The keys currently sitting in the free list, for tests that need to
+    /// observe pruning from outside this module.
+    #[cfg(test)]
+    pub(crate) fn free_keys(&self) -> Vec<TextureKey> {
+        self.inner.lock().unwrap().free.keys().copied().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::test_support::test_device;
+
+    /// Two keys with the same size but different usage must not be treated
+    /// as interchangeable: `prune_except` should only keep a pooled texture
+    /// whose *entire* key — not just its size — is still live, and drop one
+    /// that shares the live key's size but not its usage flags.
+    #[test]
+    fn prune_except_compares_full_key_not_just_size() {
+        let Some((device, _queue)) = test_device() else {
+            return;
+        };
+        let pool = TexturePool::new();
+
+        let render_attachment_key = TextureKey {
+            width: 64,
+            height: 64,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
+            sample_count: 1,
+        };
+        let storage_key = TextureKey {
+            usage: wgpu::TextureUsages::STORAGE_BINDING,
+            ..render_attachment_key
+        };
+
+        // Acquire one of each and let both return to the free list.
+        drop(pool.acquire(
+            &device,
+            "a",
+            render_attachment_key.width,
+            render_attachment_key.height,
+            render_attachment_key.format,
+            render_attachment_key.usage,
+            render_attachment_key.sample_count,
+        ));
+        drop(pool.acquire(
+            &device,
+            "b",
+            storage_key.width,
+            storage_key.height,
+            storage_key.format,
+            storage_key.usage,
+            storage_key.sample_count,
+        ));
+
+        // Only the render-attachment key is still wanted, even though the
+        // storage key has the exact same width/height.
+        pool.prune_except(&[render_attachment_key]);
+
+        let free = &pool.inner.lock().unwrap().free;
+        assert_eq!(free.keys().collect::<Vec<_>>(), vec![&render_attachment_key]);
+        assert_eq!(free[&render_attachment_key].len(), 1);
+    }
+
+    /// `prune_except` with an empty live set should drop every pooled
+    /// texture, not just ones matching nothing by coincidence.
+    #[test]
+    fn prune_except_with_no_live_keys_empties_the_pool() {
+        let Some((device, _queue)) = test_device() else {
+            return;
+        };
+        let pool = TexturePool::new();
+        drop(pool.acquire(
+            &device,
+            "a",
+            32,
+            32,
+            wgpu::TextureFormat::Rgba8Unorm,
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
+            1,
+        ));
+
+        pool.prune_except(&[]);
+
+        assert!(pool.inner.lock().unwrap().free.is_empty());
+    }
+}
+
+/// A `wgpu::Texture` borrowed from a [`TexturePool`]. Returns itself to the
+/// pool's free list on drop instead of being destroyed.
+pub struct PooledTexture {
+    texture: Option<wgpu::Texture>,
+    view: wgpu::TextureView,
+    key: TextureKey,
+    pool: Arc<Mutex<TexturePoolInner>>,
+}
+
+impl PooledTexture {
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+}
+
+impl Drop for PooledTexture {
+    fn drop(&mut self) {
+        if let Some(texture) = self.texture.take() {
+            self.pool.lock().unwrap().free.entry(self.key).or_default().push(texture);
+        }
+    }
+}